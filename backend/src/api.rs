@@ -0,0 +1,118 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Read-only query endpoints for the cryptocurrency service, analogous to
+//! the `get_utxo` endpoint of the UTXO example.
+
+use exonum::api::{self, ServiceApiBuilder, ServiceApiState};
+use exonum::blockchain::Schema as CoreSchema;
+use exonum::crypto::{Hash, PublicKey};
+
+use schema::{CurrencySchema, TxOutcome, Wallet};
+use transactions::WalletTransactions;
+
+/// Query parameters for the `get_wallet_history` endpoint.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WalletHistoryQuery {
+    /// Public key of the wallet whose history is requested.
+    pub pub_key: PublicKey,
+}
+
+/// Query parameters for the `get_wallet` endpoint.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WalletQuery {
+    /// Public key of the wallet to look up.
+    pub pub_key: PublicKey,
+}
+
+/// Query parameters for the `get_transfer` endpoint.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransferQuery {
+    /// Hash of the committed `Transfer` transaction to look up.
+    pub tx_hash: Hash,
+}
+
+/// Resolved outcome of a single `Transfer` transaction.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransferInfo {
+    /// Public key of the sender.
+    pub from: PublicKey,
+    /// Public keys of the recipients, in the order they were declared in the transaction.
+    pub recipients: Vec<PublicKey>,
+    /// Amount credited to each recipient, aligned with `recipients`.
+    pub amounts: Vec<u64>,
+    /// Whether the transfer fell within the time-window check and was executed.
+    pub within_time_window: bool,
+}
+
+/// Public API of the cryptocurrency service.
+#[derive(Debug, Clone, Copy)]
+pub struct PublicApi;
+
+impl PublicApi {
+    /// Returns the wallet for the given public key, if it has been created.
+    pub fn get_wallet(state: &ServiceApiState, query: WalletQuery) -> api::Result<Option<Wallet>> {
+        let snapshot = state.snapshot();
+        let schema = CurrencySchema::new(snapshot);
+        Ok(schema.wallet(&query.pub_key))
+    }
+
+    /// Returns the resolved outcome of a committed `Transfer` transaction.
+    pub fn get_transfer(
+        state: &ServiceApiState,
+        query: TransferQuery,
+    ) -> api::Result<Option<TransferInfo>> {
+        let snapshot = state.snapshot();
+        let core_schema = CoreSchema::new(&snapshot);
+
+        let raw = match core_schema.transactions().get(&query.tx_hash) {
+            Some(raw) => raw,
+            None => return Ok(None),
+        };
+
+        let tx = match WalletTransactions::tx_from_raw(raw.payload().clone()) {
+            Ok(WalletTransactions::Transfer(transfer)) => transfer,
+            _ => return Ok(None),
+        };
+
+        let schema = CurrencySchema::new(&snapshot);
+        let within_time_window = schema.tx_outcome(&query.tx_hash) == Some(TxOutcome::Committed);
+
+        Ok(Some(TransferInfo {
+            from: tx.from,
+            recipients: tx.recipients,
+            amounts: tx.amounts,
+            within_time_window,
+        }))
+    }
+
+    /// Returns the auditable, Merkelized transaction history of a wallet.
+    pub fn get_wallet_history(
+        state: &ServiceApiState,
+        query: WalletHistoryQuery,
+    ) -> api::Result<Vec<Hash>> {
+        let snapshot = state.snapshot();
+        let schema = CurrencySchema::new(snapshot);
+        Ok(schema.wallet_history(&query.pub_key).iter().collect())
+    }
+
+    /// Wires the endpoints into the service API builder.
+    pub fn wire(builder: &mut ServiceApiBuilder) {
+        builder
+            .public_scope()
+            .endpoint("v1/wallet", Self::get_wallet)
+            .endpoint("v1/wallet/history", Self::get_wallet_history)
+            .endpoint("v1/transfer", Self::get_transfer);
+    }
+}
@@ -0,0 +1,202 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::borrow::Cow;
+
+use exonum::crypto::{Hash, PublicKey};
+use exonum::storage::{Entry, Fork, MapIndex, ProofListIndex, Snapshot, StorageValue};
+
+use transactions::Error;
+
+/// Width, in milliseconds, of the window within which a `Transfer`'s declared `tx_time`
+/// must fall relative to the current block time.
+///
+/// `time_window_ms` reads this from storage so the window is *stored as* a parameter
+/// in the blockchain state rather than a literal in `Transfer::execute`, but nothing writes a
+/// different value there (no genesis config, no admin transaction), so in practice
+/// every node enforces this exact constant today.
+pub const DEFAULT_TIME_WINDOW_MS: i64 = 2_160_000;
+
+encoding_struct! {
+    /// Wallet struct used to persist data within the service.
+    ///
+    /// The wallet's transaction history lives entirely in the per-wallet
+    /// `wallet_history` `ProofListIndex`; there's no length/hash duplicated here; a
+    /// cached copy would drift from that list the moment something appends to it
+    /// without also updating the wallet (as `append_history` does for every rejected
+    /// `Transfer`).
+    pub struct Wallet {
+        pub_key: &PublicKey,
+        name: &str,
+        balance: u64,
+    }
+}
+
+impl Wallet {
+    /// Returns a copy of this wallet with the balance set to the given amount.
+    pub fn set_balance(self, balance: u64) -> Self {
+        Self::new(self.pub_key(), self.name(), balance)
+    }
+}
+
+/// Outcome of a wallet transaction, recorded alongside its hash in the wallet's
+/// transaction history.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum TxOutcome {
+    /// The transaction was executed and its effects applied.
+    Committed = 0,
+    /// A `Transfer` was rejected because it fell outside the timelock window.
+    RejectedTimeWindow = 1,
+    /// A `Transfer` was rejected because the sender had insufficient funds.
+    InsufficientFunds = 2,
+}
+
+impl StorageValue for TxOutcome {
+    fn into_bytes(self) -> Vec<u8> {
+        vec![self as u8]
+    }
+
+    fn from_bytes(value: Cow<[u8]>) -> Self {
+        match value[0] {
+            0 => TxOutcome::Committed,
+            1 => TxOutcome::RejectedTimeWindow,
+            _ => TxOutcome::InsufficientFunds,
+        }
+    }
+}
+
+/// Database schema for the cryptocurrency service.
+#[derive(Debug)]
+pub struct CurrencySchema<T> {
+    view: T,
+}
+
+impl<T> AsMut<T> for CurrencySchema<T> {
+    fn as_mut(&mut self) -> &mut T {
+        &mut self.view
+    }
+}
+
+impl<T> CurrencySchema<T>
+where
+    T: AsRef<Snapshot>,
+{
+    /// Creates a new schema from the database view.
+    pub fn new(view: T) -> Self {
+        CurrencySchema { view }
+    }
+
+    /// Returns `MapIndex` with wallets keyed by their public key.
+    pub fn wallets(&self) -> MapIndex<&Snapshot, PublicKey, Wallet> {
+        MapIndex::new("cryptocurrency.wallets", self.view.as_ref())
+    }
+
+    /// Returns the wallet for the given public key, if it exists.
+    pub fn wallet(&self, pub_key: &PublicKey) -> Option<Wallet> {
+        self.wallets().get(pub_key)
+    }
+
+    /// Returns the Merkelized list of transaction hashes affecting the given wallet,
+    /// in the order they were executed.
+    pub fn wallet_history(&self, pub_key: &PublicKey) -> ProofListIndex<&Snapshot, Hash> {
+        ProofListIndex::new_in_family(
+            "cryptocurrency.wallet_history",
+            pub_key,
+            self.view.as_ref(),
+        )
+    }
+
+    /// Returns the recorded outcome for a given transaction hash, if any.
+    pub fn tx_outcome(&self, tx_hash: &Hash) -> Option<TxOutcome> {
+        self.tx_outcomes().get(tx_hash)
+    }
+
+    fn tx_outcomes(&self) -> MapIndex<&Snapshot, Hash, TxOutcome> {
+        MapIndex::new("cryptocurrency.tx_outcomes", self.view.as_ref())
+    }
+
+    fn time_window_entry(&self) -> Entry<&Snapshot, i64> {
+        Entry::new("cryptocurrency.time_window_ms", self.view.as_ref())
+    }
+
+    /// Returns the width, in milliseconds, of the `Transfer` timelock window, read from
+    /// storage and falling back to [`DEFAULT_TIME_WINDOW_MS`] if unset.
+    ///
+    /// Nothing currently writes `cryptocurrency.time_window_ms` — there is no genesis
+    /// config or admin transaction that sets it — so this always returns the default.
+    /// The `Entry` lookup is kept so a future setter only has to start writing the
+    /// value; it does not make the window configurable by itself.
+    pub fn time_window_ms(&self) -> i64 {
+        self.time_window_entry().get().unwrap_or(DEFAULT_TIME_WINDOW_MS)
+    }
+}
+
+impl<'a> CurrencySchema<&'a mut Fork> {
+    /// Returns a mutable `MapIndex` with wallets keyed by their public key.
+    pub fn wallets_mut(&mut self) -> MapIndex<&mut Fork, PublicKey, Wallet> {
+        MapIndex::new("cryptocurrency.wallets", &mut self.view)
+    }
+
+    /// Creates a new wallet. Does nothing if one already exists for `key`.
+    pub fn create_wallet(&mut self, key: &PublicKey, name: &str) {
+        let wallet = {
+            let wallets = self.wallets();
+            if wallets.get(key).is_some() {
+                return;
+            }
+            Wallet::new(key, name, 0)
+        };
+        self.wallets_mut().put(key, wallet);
+    }
+
+    /// Increases the wallet's balance by `amount`, returning `Error::AmountOverflow`
+    /// rather than silently wrapping `u64`.
+    pub fn increase_wallet_balance(&mut self, wallet: Wallet, amount: u64) -> Result<(), Error> {
+        let balance = wallet
+            .balance()
+            .checked_add(amount)
+            .ok_or(Error::AmountOverflow)?;
+        let wallet = wallet.set_balance(balance);
+        self.wallets_mut().put(&wallet.pub_key(), wallet);
+        Ok(())
+    }
+
+    /// Decreases the wallet's balance by `amount`, returning `Error::AmountOverflow`
+    /// rather than silently wrapping `u64`.
+    pub fn decrease_wallet_balance(&mut self, wallet: Wallet, amount: u64) -> Result<(), Error> {
+        let balance = wallet
+            .balance()
+            .checked_sub(amount)
+            .ok_or(Error::AmountOverflow)?;
+        let wallet = wallet.set_balance(balance);
+        self.wallets_mut().put(&wallet.pub_key(), wallet);
+        Ok(())
+    }
+
+    /// Returns a mutable `ProofListIndex` with the transaction history of the given wallet.
+    pub fn wallet_history_mut(&mut self, pub_key: &PublicKey) -> ProofListIndex<&mut Fork, Hash> {
+        ProofListIndex::new_in_family("cryptocurrency.wallet_history", pub_key, &mut self.view)
+    }
+
+    fn tx_outcomes_mut(&mut self) -> MapIndex<&mut Fork, Hash, TxOutcome> {
+        MapIndex::new("cryptocurrency.tx_outcomes", &mut self.view)
+    }
+
+    /// Appends `tx_hash` to `pub_key`'s transaction history and records its outcome.
+    pub fn append_history(&mut self, pub_key: &PublicKey, tx_hash: &Hash, outcome: TxOutcome) {
+        self.wallet_history_mut(pub_key).push(*tx_hash);
+        self.tx_outcomes_mut().put(tx_hash, outcome);
+    }
+}
@@ -12,17 +12,17 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use exonum::blockchain::{ExecutionError, ExecutionResult, Transaction};
+use std::collections::HashMap;
+
+use exonum::blockchain::{ExecutionError, ExecutionResult, Transaction, TransactionSet};
 use exonum::crypto::{CryptoHash, PublicKey};
 use exonum::messages::Message;
 use exonum::storage::Fork;
-use chrono::Utc;
-use time::Duration;
-use chrono::prelude::*;
-
+use exonum_time::schema::TimeSchema;
 
 use CRYPTOCURRENCY_SERVICE_ID;
-use schema::CurrencySchema;
+use proto;
+use schema::{CurrencySchema, TxOutcome};
 
 /// Error codes emitted by wallet transactions during execution.
 #[derive(Debug, Fail)]
@@ -46,11 +46,32 @@ pub enum Error {
     #[fail(display = "Receiver doesn't exist")]
     ReceiverNotFound = 2,
 
-    /// Insufficient currency amount.
+    /// The number of recipients doesn't match the number of amounts, or the
+    /// recipient list is empty.
+    ///
+    /// Can be emitted by `Transfer`.
+    #[fail(display = "Recipients and amounts lists are empty or have different lengths")]
+    MalformedRecipientsList = 4,
+
+    /// Sender listed itself as one of the recipients.
+    ///
+    /// Can be emitted by `Transfer`.
+    #[fail(display = "Sender cannot be a recipient")]
+    SenderIsRecipient = 5,
+
+    /// Summing the requested amounts, or updating a wallet balance, overflowed
+    /// (or, for a decrease, underflowed) `u64`.
+    ///
+    /// Can be emitted by `Transfer` or `Issue`.
+    #[fail(display = "Amount overflow")]
+    AmountOverflow = 6,
+
+    /// The time oracle hasn't yet committed a block time, so the timelock check
+    /// cannot be performed deterministically.
     ///
     /// Can be emitted by `Transfer`.
-    #[fail(display = "Insufficient currency amount")]
-    InsufficientCurrencyAmount = 3,
+    #[fail(display = "Block time is not yet available")]
+    BlockTimeUnavailable = 8,
 }
 
 impl From<Error> for ExecutionError {
@@ -60,122 +81,153 @@ impl From<Error> for ExecutionError {
     }
 }
 
-transactions! {
-    pub WalletTransactions {
-        const SERVICE_ID = CRYPTOCURRENCY_SERVICE_ID;
-
-        /// Transfer `amount` of the currency from one wallet to another.
-        struct Transfer {
-            from:    &PublicKey,
-            to:      &PublicKey,
-            to_second:      &PublicKey,
-            to_third:      &PublicKey,
-            amount:  u64,
-            seed:    u64,
-            // time:   i64,
-            tx_time:    &str,
-        }
+/// Transfer `amounts` of the currency from one wallet to a variable number of
+/// recipients.
+#[derive(Serialize, Deserialize, Clone, Debug, ProtobufConvert)]
+#[exonum(pb = "proto::Transfer")]
+pub struct Transfer {
+    pub from: PublicKey,
+    pub recipients: Vec<PublicKey>,
+    pub amounts: Vec<u64>,
+    pub seed: u64,
+    pub tx_time: i64,
+}
 
-        /// Issue `amount` of the currency to the `wallet`.
-        struct Issue {
-            pub_key:  &PublicKey,
-            amount:  u64,
-            amount_second:  u64,
-            amount_third:  u64,
-            seed:    u64,
-        }
+/// Issue `amount + amount_second + amount_third` of the currency to the wallet of `pub_key`.
+#[derive(Serialize, Deserialize, Clone, Debug, ProtobufConvert)]
+#[exonum(pb = "proto::Issue")]
+pub struct Issue {
+    pub pub_key: PublicKey,
+    pub amount: u64,
+    pub amount_second: u64,
+    pub amount_third: u64,
+    pub seed: u64,
+}
 
-        /// Create wallet with the given `name`.
-        struct CreateWallet {
-            pub_key: &PublicKey,
-            name:    &str,
-        }
-    }
+/// Create wallet with the given `name`.
+#[derive(Serialize, Deserialize, Clone, Debug, ProtobufConvert)]
+#[exonum(pb = "proto::CreateWallet")]
+pub struct CreateWallet {
+    pub pub_key: PublicKey,
+    pub name: String,
 }
 
-impl Transfer {
-    fn timestamping(&self) -> i64 
-    {
-        let now = Utc::now();
-        let seconds: i64 = now.timestamp();
-        let nanoseconds: i64 = now.nanosecond() as i64;
-        (seconds * 1000) + (nanoseconds / 1000 / 1000)
-    }
+/// Transaction group defined by the service, dispatched by Exonum's message envelope.
+#[derive(Serialize, Deserialize, Clone, Debug, TransactionSet)]
+pub enum WalletTransactions {
+    /// See [`Transfer`].
+    Transfer(Transfer),
+    /// See [`Issue`].
+    Issue(Issue),
+    /// See [`CreateWallet`].
+    CreateWallet(CreateWallet),
 }
 
 impl Transaction for Transfer {
     fn verify(&self) -> bool {
-        self.verify_signature(self.from())
+        self.verify_signature(&self.from)
     }
 
     fn execute(&self, fork: &mut Fork) -> ExecutionResult {
+        let block_time = TimeSchema::new(&*fork)
+            .time()
+            .get()
+            .ok_or(Error::BlockTimeUnavailable)?;
+
         let mut schema = CurrencySchema::new(fork);
 
-        let from = self.from();
-        let to = self.to();
-        let to_second = self.to_second();
-        let to_third = self.to_third();
+        let from = &self.from;
+        let recipients = &self.recipients;
+        let amounts = &self.amounts;
         let hash = self.hash();
-        let amount = self.amount();
 
-        // let time = self.time();
-        let tx_time_str = self.tx_time();
-        let tx_time = tx_time_str.parse::<i64>().unwrap();
+        if recipients.is_empty() || recipients.len() != amounts.len() {
+            Err(Error::MalformedRecipientsList)?
+        }
+
+        if recipients.iter().any(|to| to == from) {
+            Err(Error::SenderIsRecipient)?
+        }
+
+        let tx_time = self.tx_time;
 
         let sender = schema.wallet(from).ok_or(Error::SenderNotFound)?;
 
-        let receiver = schema.wallet(to).ok_or(Error::ReceiverNotFound)?;
-        let receiver_second = schema.wallet(to_second).ok_or(Error::ReceiverNotFound)?;
-        let receiver_third = schema.wallet(to_third).ok_or(Error::ReceiverNotFound)?;
+        // Validate every recipient exists up front; duplicates are aggregated below and
+        // re-resolved from `schema` at credit time so repeated addresses are summed rather
+        // than overwritten with a stale balance.
+        for to in recipients.iter() {
+            schema.wallet(to).ok_or(Error::ReceiverNotFound)?;
+        }
+
+        let total_amount = amounts
+            .iter()
+            .try_fold(0u64, |total, amount| total.checked_add(*amount))
+            .ok_or(Error::AmountOverflow)?;
 
-        if sender.balance() < amount {
-            Err(Error::InsufficientCurrencyAmount)?
+        let mut credits: HashMap<PublicKey, u64> = HashMap::new();
+        for (to, amount) in recipients.iter().zip(amounts.iter()) {
+            let credit = credits.entry(*to).or_insert(0);
+            *credit = credit.checked_add(*amount).ok_or(Error::AmountOverflow)?;
         }
 
-        let current_time = self.timestamping();
+        if sender.balance() < total_amount {
+            // See the time-window check below for why this records the rejection via
+            // `TxOutcome` and returns `Ok` instead of an `Error` variant.
+            schema.append_history(from, &hash, TxOutcome::InsufficientFunds);
+            return Ok(());
+        }
+
+        let current_time = block_time.timestamp_millis();
+        let window_ms = schema.time_window_ms();
 
-        if current_time > tx_time && current_time < (tx_time + 2160000)
+        if current_time > tx_time && current_time < tx_time.checked_add(window_ms).unwrap_or(i64::max_value())
         {
-            println!("=========== Success =============");
-            println!("=========== Previous time: {} =============", tx_time);
-            println!("=========== Current time: {} =============", current_time);
-            
-            let total_amount = amount * 3;
-            schema.decrease_wallet_balance(sender, total_amount, &hash);
-            schema.increase_wallet_balance(receiver, amount, &hash);
-            schema.increase_wallet_balance(receiver_second, amount, &hash);
-            schema.increase_wallet_balance(receiver_third, amount, &hash);
+            schema.decrease_wallet_balance(sender, total_amount)?;
+
+            schema.append_history(from, &hash, TxOutcome::Committed);
+            for (to, amount) in credits {
+                let receiver = schema.wallet(&to).ok_or(Error::ReceiverNotFound)?;
+                schema.increase_wallet_balance(receiver, amount)?;
+                schema.append_history(&to, &hash, TxOutcome::Committed);
+            }
+
+            Ok(())
         }
         else
         {
-            println!("=========== Failure =============");
-            println!("=========== Previous time: {} =============", tx_time);
-            println!("=========== Current time: {} =============", current_time);
+            // There is deliberately no `Error::TransferExpired` here: returning `Err`
+            // would discard the `append_history` write below along with the rest of the
+            // fork changes, defeating the point of a provable rejection log. The
+            // trade-off is that `core_schema.transaction_results()` reports this (and
+            // the insufficient-funds case above) as committed rather than failed — the
+            // service-level outcome now lives in `TxOutcome`/`wallet_history`
+            // (see `CurrencySchema::tx_outcome`, used by `api::PublicApi::get_transfer`)
+            // instead of the framework's transaction result code.
+            schema.append_history(from, &hash, TxOutcome::RejectedTimeWindow);
+            Ok(())
         }
-
-        // schema.decrease_wallet_balance(sender, amount, &hash);
-        // schema.increase_wallet_balance(receiver, amount, &hash);
-
-        Ok(())
     }
 }
 
 impl Transaction for Issue {
     fn verify(&self) -> bool {
-        self.verify_signature(self.pub_key())
+        self.verify_signature(&self.pub_key)
     }
 
     fn execute(&self, fork: &mut Fork) -> ExecutionResult {
         let mut schema = CurrencySchema::new(fork);
-        let pub_key = self.pub_key();
+        let pub_key = &self.pub_key;
         let hash = self.hash();
 
         if let Some(wallet) = schema.wallet(pub_key) {
-            let amount = self.amount();
-            let amount_second = self.amount_second();
-            let amount_third = self.amount_third();
-            let sum_amount = amount_third + amount + amount_second;
-            schema.increase_wallet_balance(wallet, sum_amount, &hash);
+            let sum_amount = self
+                .amount
+                .checked_add(self.amount_second)
+                .and_then(|sum| sum.checked_add(self.amount_third))
+                .ok_or(Error::AmountOverflow)?;
+            schema.increase_wallet_balance(wallet, sum_amount)?;
+            schema.append_history(pub_key, &hash, TxOutcome::Committed);
             Ok(())
         } else {
             Err(Error::ReceiverNotFound)?
@@ -185,20 +237,150 @@ impl Transaction for Issue {
 
 impl Transaction for CreateWallet {
     fn verify(&self) -> bool {
-        self.verify_signature(self.pub_key())
+        self.verify_signature(&self.pub_key)
     }
 
     fn execute(&self, fork: &mut Fork) -> ExecutionResult {
         let mut schema = CurrencySchema::new(fork);
-        let pub_key = self.pub_key();
+        let pub_key = &self.pub_key;
         let hash = self.hash();
 
         if schema.wallet(pub_key).is_none() {
-            let name = self.name();
-            schema.create_wallet(pub_key, name, &hash);
+            schema.create_wallet(pub_key, &self.name);
+            schema.append_history(pub_key, &hash, TxOutcome::Committed);
             Ok(())
         } else {
             Err(Error::WalletAlreadyExists)?
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use exonum::crypto::gen_keypair;
+    use exonum::storage::{Database, MemoryDB};
+    use exonum_time::schema::TimeSchema;
+
+    use super::*;
+
+    fn set_block_time(fork: &mut Fork, millis: i64) {
+        TimeSchema::new(fork).time().set(Utc.timestamp_millis(millis));
+    }
+
+    fn create_funded_wallet(
+        schema: &mut CurrencySchema<&mut Fork>,
+        name: &str,
+        balance: u64,
+    ) -> PublicKey {
+        let (pub_key, _) = gen_keypair();
+        schema.create_wallet(&pub_key, name);
+        let wallet = schema.wallet(&pub_key).unwrap();
+        schema.increase_wallet_balance(wallet, balance).unwrap();
+        pub_key
+    }
+
+    #[test]
+    fn transfer_aggregates_duplicate_recipients() {
+        let db = MemoryDB::new();
+        let mut fork = db.fork();
+        set_block_time(&mut fork, 1_000);
+
+        let (from, to) = {
+            let mut schema = CurrencySchema::new(&mut fork);
+            let from = create_funded_wallet(&mut schema, "sender", 100);
+            let to = create_funded_wallet(&mut schema, "receiver", 0);
+            (from, to)
+        };
+
+        let transfer = Transfer {
+            from,
+            recipients: vec![to, to],
+            amounts: vec![30, 20],
+            seed: 0,
+            tx_time: 1_000,
+        };
+        transfer.execute(&mut fork).expect("transfer should commit");
+
+        let schema = CurrencySchema::new(&fork);
+        assert_eq!(schema.wallet(&to).unwrap().balance(), 50);
+        assert_eq!(schema.wallet(&from).unwrap().balance(), 50);
+        assert_eq!(
+            schema.tx_outcome(&transfer.hash()),
+            Some(TxOutcome::Committed)
+        );
+    }
+
+    #[test]
+    fn transfer_rejects_amount_sum_overflow() {
+        let db = MemoryDB::new();
+        let mut fork = db.fork();
+        set_block_time(&mut fork, 1_000);
+
+        let (from, to_a, to_b) = {
+            let mut schema = CurrencySchema::new(&mut fork);
+            let from = create_funded_wallet(&mut schema, "sender", 100);
+            let to_a = create_funded_wallet(&mut schema, "receiver-a", 0);
+            let to_b = create_funded_wallet(&mut schema, "receiver-b", 0);
+            (from, to_a, to_b)
+        };
+
+        let transfer = Transfer {
+            from,
+            recipients: vec![to_a, to_b],
+            amounts: vec![u64::max_value(), 1],
+            seed: 0,
+            tx_time: 1_000,
+        };
+        let err = transfer
+            .execute(&mut fork)
+            .expect_err("summing the amounts should overflow u64");
+        assert_eq!(err.error_code(), Error::AmountOverflow as u8);
+
+        // The overflow is detected before any balance is written, so the sender's
+        // balance must be untouched.
+        let schema = CurrencySchema::new(&fork);
+        assert_eq!(schema.wallet(&from).unwrap().balance(), 100);
+    }
+
+    #[test]
+    fn transfer_at_window_boundary_is_rejected_but_recorded() {
+        let db = MemoryDB::new();
+        let mut fork = db.fork();
+
+        let tx_time = 1_000;
+        // The window check is `current_time < tx_time + window_ms`, so landing exactly
+        // on the boundary must be rejected, not accepted.
+        set_block_time(&mut fork, tx_time + ::schema::DEFAULT_TIME_WINDOW_MS);
+
+        let (from, to) = {
+            let mut schema = CurrencySchema::new(&mut fork);
+            let from = create_funded_wallet(&mut schema, "sender", 100);
+            let to = create_funded_wallet(&mut schema, "receiver", 0);
+            (from, to)
+        };
+
+        let transfer = Transfer {
+            from,
+            recipients: vec![to],
+            amounts: vec![10],
+            seed: 0,
+            tx_time,
+        };
+        transfer
+            .execute(&mut fork)
+            .expect("a rejected transfer still returns Ok so its history write survives");
+
+        let schema = CurrencySchema::new(&fork);
+        assert_eq!(schema.wallet(&from).unwrap().balance(), 100);
+        assert_eq!(schema.wallet(&to).unwrap().balance(), 0);
+        assert_eq!(
+            schema.tx_outcome(&transfer.hash()),
+            Some(TxOutcome::RejectedTimeWindow)
+        );
+        assert_eq!(
+            schema.wallet_history(&from).iter().last(),
+            Some(transfer.hash())
+        );
+    }
+}